@@ -1,26 +1,311 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod remote;
+
 use portable_pty::{native_pty_system, Child, CommandBuilder, MasterPty, PtySize};
-use serde::Serialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use tauri::{AppHandle, Emitter, State};
+use std::time::Instant;
+use tauri::{AppHandle, Emitter, Manager, State};
 use uuid::Uuid;
 
-struct PtySession {
-    name: String,
-    role: String,
+pub(crate) struct PtySession {
+    pub(crate) name: String,
+    pub(crate) role: String,
+    pub(crate) host: String,
+    pub(crate) state: AgentState,
+    pub(crate) runtime: SessionRuntime,
+    pub(crate) transcript: Transcript,
+}
+
+/// What's left of a `PtySession` once its process/connection is gone:
+/// `SessionRuntime` is dropped along with it, but `transcript` is kept
+/// reachable under the same id in `AppState::completed` so `get_scrollback`
+/// and `export_transcript` still work right after an agent exits or
+/// disconnects — exactly when reattach replay and audit export are needed.
+pub(crate) struct CompletedSession {
+    pub(crate) name: String,
+    pub(crate) role: String,
+    pub(crate) transcript: Transcript,
+}
+
+/// The process side of a session, absent while an agent is `Queued` behind
+/// the concurrency limiter and populated once either `start_agent_process`
+/// spawns a local `pi`, or a `remote::RemoteSession` registers over the
+/// TLS control channel.
+pub(crate) enum SessionRuntime {
+    Queued,
+    Running(RunningAgent),
+    Remote(remote::RemoteSession),
+}
+
+impl SessionRuntime {
+    /// Borrows the session as a generic `AgentHandle`, or `None` while it's
+    /// still `Queued` and has neither a local process nor a remote link to
+    /// address commands to.
+    fn handle_mut(&mut self) -> Option<&mut dyn AgentHandle> {
+        match self {
+            SessionRuntime::Running(running) => Some(running),
+            SessionRuntime::Remote(remote) => Some(remote),
+            SessionRuntime::Queued => None,
+        }
+    }
+}
+
+/// Common interface for anything that can receive PTY input/resize/kill
+/// commands, whether that's a local `RunningAgent` or a `remote::RemoteSession`
+/// proxying a PTY over the wire. `write_pty`/`resize_pty`/`kill_agent` dispatch
+/// through this instead of matching on `SessionRuntime` themselves, so a new
+/// kind of backing session doesn't require touching the command handlers.
+pub(crate) trait AgentHandle {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()>;
+    fn resize(&mut self, cols: u16, rows: u16) -> std::io::Result<()>;
+    fn kill(&mut self) -> std::io::Result<()>;
+}
+
+pub(crate) struct RunningAgent {
     pty: Box<dyn MasterPty + Send>,
     writer: Box<dyn Write + Send>,
     child: Box<dyn Child + Send>,
 }
 
-#[derive(Default)]
+impl AgentHandle for RunningAgent {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.writer.write_all(data)?;
+        self.writer.flush()
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> std::io::Result<()> {
+        self.pty
+            .resize(PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            })
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        self.child.kill()
+    }
+}
+
+/// Bound on the in-memory scrollback ring buffer kept per agent, like a CI
+/// runner tailing the last bit of a job log.
+const SCROLLBACK_CAPACITY_BYTES: usize = 256 * 1024;
+
+struct TranscriptChunk {
+    elapsed_ms: u64,
+    len: usize,
+}
+
+/// Per-agent output recording: a bounded ring buffer for instant scrollback
+/// replay on reattach, plus an optional append-only on-disk log for a
+/// durable audit trail. `chunk_index` tracks chunk boundaries/timestamps so
+/// `export_transcript` can annotate the export per-chunk; it's trimmed in
+/// lockstep with the ring when there's no disk file to fall back on, and
+/// left untrimmed (it's tiny) when there is, since the disk file itself is
+/// the untrimmed source of truth.
+pub(crate) struct Transcript {
+    started: Instant,
+    ring: VecDeque<u8>,
+    chunk_index: Vec<TranscriptChunk>,
+    disk_path: Option<PathBuf>,
+    disk_file: Option<std::fs::File>,
+}
+
+impl Transcript {
+    pub(crate) fn new(disk_path: Option<PathBuf>) -> Self {
+        let disk_file = disk_path.as_ref().and_then(|path| {
+            if let Some(parent) = path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .ok()
+        });
+
+        Transcript {
+            started: Instant::now(),
+            ring: VecDeque::new(),
+            chunk_index: Vec::new(),
+            disk_path,
+            disk_file,
+        }
+    }
+
+    pub(crate) fn record(&mut self, data: &[u8]) {
+        self.chunk_index.push(TranscriptChunk {
+            elapsed_ms: self.started.elapsed().as_millis() as u64,
+            len: data.len(),
+        });
+        self.ring.extend(data.iter().copied());
+
+        if self.disk_file.is_none() {
+            while self.ring.len() > SCROLLBACK_CAPACITY_BYTES {
+                let overflow = self.ring.len() - SCROLLBACK_CAPACITY_BYTES;
+                match self.chunk_index.first_mut() {
+                    Some(oldest) => {
+                        let trimmed = overflow.min(oldest.len);
+                        for _ in 0..trimmed {
+                            self.ring.pop_front();
+                        }
+                        oldest.len -= trimmed;
+                        if oldest.len == 0 {
+                            self.chunk_index.remove(0);
+                        }
+                    }
+                    None => {
+                        self.ring.pop_front();
+                    }
+                }
+            }
+        } else {
+            while self.ring.len() > SCROLLBACK_CAPACITY_BYTES {
+                self.ring.pop_front();
+            }
+        }
+
+        if let Some(file) = &mut self.disk_file {
+            let _ = file.write_all(data);
+        }
+    }
+
+    fn scrollback(&self) -> Vec<u8> {
+        self.ring.iter().copied().collect()
+    }
+
+    /// Flushes the full recording to `target`. With `with_timestamps`, each
+    /// recorded chunk is annotated with its `[+<ms>]` offset from spawn;
+    /// otherwise the raw bytes are written as-is.
+    fn export(&mut self, target: &std::path::Path, with_timestamps: bool) -> std::io::Result<()> {
+        if let Some(file) = &mut self.disk_file {
+            file.flush()?;
+        }
+
+        if !with_timestamps {
+            return match &self.disk_path {
+                Some(disk_path) => {
+                    std::fs::copy(disk_path, target)?;
+                    Ok(())
+                }
+                None => std::fs::write(target, self.scrollback()),
+            };
+        }
+
+        let raw = match &self.disk_path {
+            Some(disk_path) => std::fs::read(disk_path)?,
+            None => self.scrollback(),
+        };
+
+        let mut annotated = String::new();
+        let mut offset = 0;
+        for chunk in &self.chunk_index {
+            if offset >= raw.len() {
+                break;
+            }
+            let end = (offset + chunk.len).min(raw.len());
+            annotated.push_str(&format!(
+                "[+{}ms] {}\n",
+                chunk.elapsed_ms,
+                String::from_utf8_lossy(&raw[offset..end])
+            ));
+            offset = end;
+        }
+
+        std::fs::write(target, annotated)
+    }
+}
+
+fn transcript_path(id: &str) -> PathBuf {
+    resolve_project_root()
+        .join(".hive")
+        .join("transcripts")
+        .join(format!("{}.log", id))
+}
+
 struct AppState {
     sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: Arc<Mutex<HashMap<String, CompletedSession>>>,
+    jobs: Arc<Mutex<HashMap<String, JobResult>>>,
+    tokens: Arc<TokenPool>,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        AppState {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+            completed: Arc::new(Mutex::new(HashMap::new())),
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            tokens: Arc::new(TokenPool::new(DEFAULT_MAX_CONCURRENCY)),
+        }
+    }
+}
+
+/// Default cap on concurrently *running* agents before `set_max_concurrency`
+/// is called; queued agents beyond this start as tokens free up.
+const DEFAULT_MAX_CONCURRENCY: usize = 4;
+
+/// A jobserver-style counting semaphore, as used by rebel-runner's
+/// `jobserver`, gating how many agents may be spawned (not merely tracked)
+/// at once. `spawn_agent` acquires a token before `spawn_command`; agents
+/// that can't get one sit in `AgentState::Queued` until one frees up.
+struct TokenPool {
+    inner: Mutex<TokenPoolState>,
+    condvar: Condvar,
+}
+
+struct TokenPoolState {
+    max: usize,
+    in_use: usize,
+}
+
+impl TokenPool {
+    fn new(max: usize) -> Self {
+        TokenPool {
+            inner: Mutex::new(TokenPoolState { max, in_use: 0 }),
+            condvar: Condvar::new(),
+        }
+    }
+
+    /// Acquires a token only if one is immediately available.
+    fn try_acquire(&self) -> bool {
+        let mut state = self.inner.lock().unwrap();
+        if state.in_use < state.max {
+            state.in_use += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Blocks the calling thread until a token is available.
+    fn acquire_blocking(&self) {
+        let mut state = self.inner.lock().unwrap();
+        while state.in_use >= state.max {
+            state = self.condvar.wait(state).unwrap();
+        }
+        state.in_use += 1;
+    }
+
+    fn release(&self) {
+        let mut state = self.inner.lock().unwrap();
+        state.in_use = state.in_use.saturating_sub(1);
+        self.condvar.notify_one();
+    }
+
+    fn set_max(&self, max: usize) {
+        let mut state = self.inner.lock().unwrap();
+        state.max = max;
+        self.condvar.notify_all();
+    }
 }
 
 #[derive(Clone, Serialize)]
@@ -35,18 +320,170 @@ struct AgentInfo {
     id: String,
     name: String,
     role: String,
+    host: String,
+    state: AgentState,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct PtyOutputEvent {
+    pub(crate) id: String,
+    pub(crate) data: Vec<u8>,
 }
 
 #[derive(Clone, Serialize)]
-struct PtyOutputEvent {
+pub(crate) struct AgentExitedEvent {
+    pub(crate) id: String,
+    pub(crate) code: Option<i32>,
+}
+
+#[derive(Clone, Serialize)]
+struct AgentStateChangedEvent {
     id: String,
-    data: Vec<u8>,
+    state: AgentState,
 }
 
 #[derive(Clone, Serialize)]
-struct AgentExitedEvent {
+struct AgentQueuedEvent {
     id: String,
-    code: Option<i32>,
+}
+
+#[derive(Clone, Serialize)]
+pub(crate) struct AgentStartedEvent {
+    pub(crate) id: String,
+}
+
+/// Result of a single non-interactive `dispatch_job` run, modeled on unki's
+/// `ExecResult`. Jobs are one-shot `pi` invocations outside the interactive
+/// PTY, so stdout/stderr come back as complete buffers rather than a stream.
+#[derive(Clone, Serialize)]
+struct JobResult {
+    id: String,
+    agent_id: String,
+    exit_code: Option<i32>,
+    stdout: String,
+    stderr: String,
+    duration_ms: u64,
+}
+
+/// Lifecycle of a spawned agent, mirroring the state model unki uses for its
+/// server-managed agents. `Starting`/`Running`/`Exited`/`Crashed` are driven
+/// by real process signals; `Idle`/`Busy` are reported by the frontend via
+/// `set_agent_state` based on what the agent is doing inside its PTY.
+#[derive(Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub(crate) enum AgentState {
+    Queued,
+    Starting,
+    Running,
+    Idle,
+    Busy,
+    Exited { code: Option<i32> },
+    Crashed,
+}
+
+/// States a frontend is allowed to report through `set_agent_state`; the
+/// terminal states are only ever reached from the backend's own exit
+/// handling (a session's reader thread, or `fail_to_start`).
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum AgentActivity {
+    Idle,
+    Busy,
+}
+
+pub(crate) fn emit_state_changed(app: &AppHandle, id: &str, state: &AgentState) {
+    let _ = app.emit(
+        "agent-state-changed",
+        AgentStateChangedEvent {
+            id: id.to_string(),
+            state: state.clone(),
+        },
+    );
+}
+
+/// Confines a spawned agent to a mount/PID/network namespace with bind mounts
+/// and cgroup v2 resource limits, borrowing the split between namespace setup
+/// and cgroup bookkeeping that the rebel build system's `ns`/`task` modules
+/// use for its own job runner. Only supported on Linux; `spawn_agent` rejects
+/// a `sandbox` argument on other targets rather than silently running the
+/// agent unconfined.
+#[derive(Clone, Deserialize)]
+struct SandboxSpec {
+    allowed_paths: Vec<String>,
+    workspace: String,
+    mem_limit_mb: Option<u64>,
+    cpu_quota: Option<f32>,
+}
+
+#[cfg(target_os = "linux")]
+fn build_sandboxed_command(program: &str, args: &[String], spec: &SandboxSpec) -> CommandBuilder {
+    let mut command = CommandBuilder::new("bwrap");
+    command.arg("--die-with-parent");
+    command.arg("--unshare-pid");
+    command.arg("--unshare-net");
+
+    for path in &spec.allowed_paths {
+        command.arg("--ro-bind");
+        command.arg(path);
+        command.arg(path);
+    }
+
+    command.arg("--bind");
+    command.arg(&spec.workspace);
+    command.arg(&spec.workspace);
+    command.arg("--proc");
+    command.arg("/proc");
+    command.arg("--dev");
+    command.arg("/dev");
+    command.arg("--chdir");
+    command.arg(&spec.workspace);
+
+    command.arg(program);
+    for arg in args {
+        command.arg(arg);
+    }
+
+    command
+}
+
+/// Moves `pid` into a per-agent cgroup v2 leaf under `/sys/fs/cgroup/hive`
+/// and applies the requested memory/CPU limits. Best-effort: a sandboxed
+/// agent that can't get cgroup limits (e.g. no root, no cgroup v2 mount)
+/// still runs namespaced, just without the resource cap.
+#[cfg(target_os = "linux")]
+fn apply_cgroup_limits(pid: u32, id: &str, spec: &SandboxSpec) -> std::io::Result<()> {
+    let cgroup_path = PathBuf::from("/sys/fs/cgroup/hive").join(id);
+    std::fs::create_dir_all(&cgroup_path)?;
+
+    if let Some(mem_limit_mb) = spec.mem_limit_mb {
+        std::fs::write(
+            cgroup_path.join("memory.max"),
+            (mem_limit_mb * 1024 * 1024).to_string(),
+        )?;
+    }
+
+    if let Some(cpu_quota) = spec.cpu_quota {
+        let period_us = 100_000u64;
+        let quota_us = (cpu_quota * period_us as f32) as u64;
+        std::fs::write(
+            cgroup_path.join("cpu.max"),
+            format!("{} {}", quota_us, period_us),
+        )?;
+    }
+
+    std::fs::write(cgroup_path.join("cgroup.procs"), pid.to_string())?;
+    Ok(())
+}
+
+/// Removes the per-agent cgroup leaf `apply_cgroup_limits` created, once the
+/// agent it was governing has exited or been killed. Best-effort, same as
+/// `apply_cgroup_limits`: the kernel only lets an empty cgroup be removed, so
+/// this is a no-op (not an error worth surfacing) if the leaf is already gone
+/// or the process somehow left something behind.
+#[cfg(target_os = "linux")]
+fn remove_cgroup(id: &str) {
+    let cgroup_path = PathBuf::from("/sys/fs/cgroup/hive").join(id);
+    let _ = std::fs::remove_dir(&cgroup_path);
 }
 
 fn resolve_project_root() -> PathBuf {
@@ -58,55 +495,346 @@ fn resolve_project_root() -> PathBuf {
     std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
 }
 
-fn build_pi_command(name: &str, role: &str, id: &str) -> CommandBuilder {
+/// A named agent invocation loaded from `hive.toml` at the project root,
+/// following hooky's `config.toml`-driven setup. `spawn_agent` picks one by
+/// name and layers per-spawn `SpawnOverrides` on top; omitting a template
+/// falls back to the original hardcoded `pi -e src/index.ts` invocation.
+#[derive(Clone, Deserialize)]
+struct RoleTemplate {
+    #[serde(default = "default_template_command")]
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: HashMap<String, String>,
+    cwd: Option<String>,
+    #[serde(default = "default_template_cols")]
+    cols: u16,
+    #[serde(default = "default_template_rows")]
+    rows: u16,
+}
+
+fn default_template_command() -> String {
+    "pi".to_string()
+}
+
+fn default_template_cols() -> u16 {
+    120
+}
+
+fn default_template_rows() -> u16 {
+    32
+}
+
+#[derive(Default, Deserialize)]
+struct HiveConfig {
+    #[serde(default)]
+    templates: HashMap<String, RoleTemplate>,
+    #[serde(default)]
+    remote: Option<remote::RemoteConfig>,
+}
+
+fn load_hive_config() -> HiveConfig {
+    let path = resolve_project_root().join("hive.toml");
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// Per-spawn overrides merged onto a `RoleTemplate` (or onto the legacy
+/// hardcoded invocation, when no template is named).
+#[derive(Clone, Default, Deserialize)]
+struct SpawnOverrides {
+    args: Option<Vec<String>>,
+    env: Option<HashMap<String, String>>,
+    cwd: Option<String>,
+    cols: Option<u16>,
+    rows: Option<u16>,
+}
+
+struct ResolvedAgentSpec {
+    command: String,
+    args: Vec<String>,
+    env: HashMap<String, String>,
+    cwd: PathBuf,
+    cols: u16,
+    rows: u16,
+}
+
+fn resolve_agent_spec(template: Option<&RoleTemplate>, overrides: &SpawnOverrides) -> ResolvedAgentSpec {
+    let root = resolve_project_root();
+
+    match template {
+        Some(template) => {
+            let mut env = template.env.clone();
+            if let Some(extra) = &overrides.env {
+                env.extend(extra.clone());
+            }
+
+            ResolvedAgentSpec {
+                command: template.command.clone(),
+                args: overrides.args.clone().unwrap_or_else(|| template.args.clone()),
+                env,
+                cwd: overrides
+                    .cwd
+                    .clone()
+                    .or_else(|| template.cwd.clone())
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| root.clone()),
+                cols: overrides.cols.unwrap_or(template.cols),
+                rows: overrides.rows.unwrap_or(template.rows),
+            }
+        }
+        None => {
+            let extension = root.join("src").join("index.ts");
+            let args = overrides.args.clone().unwrap_or_else(|| {
+                let mut args = Vec::new();
+                if extension.exists() {
+                    args.push("-e".to_string());
+                    args.push(extension.to_string_lossy().to_string());
+                }
+                args
+            });
+
+            ResolvedAgentSpec {
+                command: "pi".to_string(),
+                args,
+                env: overrides.env.clone().unwrap_or_default(),
+                cwd: overrides.cwd.clone().map(PathBuf::from).unwrap_or_else(|| root.clone()),
+                cols: overrides.cols.unwrap_or(default_template_cols()),
+                rows: overrides.rows.unwrap_or(default_template_rows()),
+            }
+        }
+    }
+}
+
+fn build_pi_command(name: &str, role: &str, id: &str, spec: &ResolvedAgentSpec, sandbox: Option<&SandboxSpec>) -> CommandBuilder {
+    #[cfg(target_os = "linux")]
+    let mut command = match sandbox {
+        Some(sandbox_spec) => build_sandboxed_command(&spec.command, &spec.args, sandbox_spec),
+        None => {
+            let mut command = CommandBuilder::new(&spec.command);
+            for arg in &spec.args {
+                command.arg(arg);
+            }
+            command
+        }
+    };
+
+    #[cfg(not(target_os = "linux"))]
+    let mut command = {
+        let mut command = CommandBuilder::new(&spec.command);
+        for arg in &spec.args {
+            command.arg(arg);
+        }
+        command
+    };
+
+    match sandbox {
+        Some(sandbox_spec) if cfg!(target_os = "linux") => command.cwd(&sandbox_spec.workspace),
+        _ => command.cwd(&spec.cwd),
+    };
+    for (key, value) in &spec.env {
+        command.env(key, value);
+    }
+    command.env("HIVE_NAME", name);
+    command.env("HIVE_ROLE", role);
+    command.env("HIVE_ID", id);
+    command.env("HIVE_INTERACTIVE", "1");
+    command
+}
+
+/// Builds a one-shot, non-interactive `pi` invocation for `dispatch_job`.
+/// Shares `build_pi_command`'s binary/extension/env conventions but wires
+/// stdio as pipes instead of a PTY since no terminal is attached.
+fn build_job_command(name: &str, role: &str, id: &str) -> std::process::Command {
     let root = resolve_project_root();
     let extension = root.join("src").join("index.ts");
 
-    let mut command = CommandBuilder::new("pi");
+    let mut command = std::process::Command::new("pi");
     if extension.exists() {
         command.arg("-e");
         command.arg(extension.to_string_lossy().to_string());
     }
 
-    command.cwd(root);
+    command.current_dir(root);
     command.env("HIVE_NAME", name);
     command.env("HIVE_ROLE", role);
     command.env("HIVE_ID", id);
-    command.env("HIVE_INTERACTIVE", "1");
+    command.env("HIVE_INTERACTIVE", "0");
+    command.stdin(std::process::Stdio::piped());
+    command.stdout(std::process::Stdio::piped());
+    command.stderr(std::process::Stdio::piped());
     command
 }
 
+fn record_job_result(jobs: &Arc<Mutex<HashMap<String, JobResult>>>, app: &AppHandle, result: JobResult) {
+    if let Ok(mut jobs) = jobs.lock() {
+        jobs.insert(result.id.clone(), result.clone());
+    }
+    let _ = app.emit("job-completed", result);
+}
+
 #[tauri::command]
-fn spawn_agent(name: String, role: String, app: AppHandle, state: State<AppState>) -> Result<SpawnResult, String> {
-    {
+fn dispatch_job(agent_id: String, payload: String, app: AppHandle, state: State<AppState>) -> Result<String, String> {
+    let (name, role) = {
         let sessions = state
             .sessions
             .lock()
             .map_err(|_| "failed to lock session map".to_string())?;
 
-        if sessions.values().any(|s| s.name == name) {
-            return Err(format!("agent name \"{}\" is already running", name));
+        let session = sessions
+            .get(&agent_id)
+            .ok_or_else(|| format!("unknown agent id {}", agent_id))?;
+
+        if matches!(session.runtime, SessionRuntime::Remote(_)) {
+            return Err("dispatch_job does not yet support agents running on a remote worker".to_string());
         }
-    }
 
-    let id = Uuid::new_v4().to_string();
+        (session.name.clone(), session.role.clone())
+    };
+
+    let job_id = Uuid::new_v4().to_string();
+    let jobs_for_thread = Arc::clone(&state.jobs);
+    let tokens_for_thread = Arc::clone(&state.tokens);
+    let app_for_thread = app.clone();
+    let agent_id_for_thread = agent_id.clone();
+    let job_id_for_thread = job_id.clone();
+
+    thread::spawn(move || {
+        let started = Instant::now();
+
+        // Jobs share the same concurrency cap as interactive agents (see
+        // `TokenPool`), so a fanned-out batch of jobs can't run more `pi`
+        // processes at once than `set_max_concurrency` allows.
+        tokens_for_thread.acquire_blocking();
+
+        let mut command = build_job_command(&name, &role, &job_id_for_thread);
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) => {
+                tokens_for_thread.release();
+                record_job_result(
+                    &jobs_for_thread,
+                    &app_for_thread,
+                    JobResult {
+                        id: job_id_for_thread,
+                        agent_id: agent_id_for_thread,
+                        exit_code: None,
+                        stdout: String::new(),
+                        stderr: format!("failed to spawn pi: {}", err),
+                        duration_ms: started.elapsed().as_millis() as u64,
+                    },
+                );
+                return;
+            }
+        };
+
+        // Write the payload from its own thread: `pi` may start echoing
+        // output before it has finished reading stdin, and writing the full
+        // payload here with `wait_with_output`'s readers not yet attached
+        // would deadlock once both the stdin and stdout pipe buffers fill.
+        if let Some(mut stdin) = child.stdin.take() {
+            thread::spawn(move || {
+                let _ = stdin.write_all(payload.as_bytes());
+            });
+        }
+
+        let output = match child.wait_with_output() {
+            Ok(output) => output,
+            Err(err) => {
+                tokens_for_thread.release();
+                record_job_result(
+                    &jobs_for_thread,
+                    &app_for_thread,
+                    JobResult {
+                        id: job_id_for_thread,
+                        agent_id: agent_id_for_thread,
+                        exit_code: None,
+                        stdout: String::new(),
+                        stderr: format!("failed to wait for pi: {}", err),
+                        duration_ms: started.elapsed().as_millis() as u64,
+                    },
+                );
+                return;
+            }
+        };
+
+        tokens_for_thread.release();
+        record_job_result(
+            &jobs_for_thread,
+            &app_for_thread,
+            JobResult {
+                id: job_id_for_thread,
+                agent_id: agent_id_for_thread,
+                exit_code: output.status.code(),
+                stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+                duration_ms: started.elapsed().as_millis() as u64,
+            },
+        );
+    });
+
+    Ok(job_id)
+}
+
+#[tauri::command]
+fn poll_completed_jobs(state: State<AppState>) -> Result<Vec<JobResult>, String> {
+    let mut jobs = state
+        .jobs
+        .lock()
+        .map_err(|_| "failed to lock job map".to_string())?;
+
+    Ok(jobs.drain().map(|(_, result)| result).collect())
+}
+
+/// Actually opens the PTY and spawns `pi`, then installs the reader thread
+/// that drives the rest of the lifecycle. Called either inline from
+/// `spawn_agent` (a token was free) or from a queued-agent thread once
+/// `TokenPool::acquire_blocking` returns.
+fn start_agent_process(
+    id: String,
+    name: String,
+    role: String,
+    sandbox: Option<SandboxSpec>,
+    template_name: Option<String>,
+    overrides: SpawnOverrides,
+    app: AppHandle,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: Arc<Mutex<HashMap<String, CompletedSession>>>,
+    tokens: Arc<TokenPool>,
+) -> Result<(), String> {
+    let config = load_hive_config();
+    let template = template_name.and_then(|name| config.templates.get(&name).cloned());
+    let spec = resolve_agent_spec(template.as_ref(), &overrides);
 
     let pty_system = native_pty_system();
     let pair = pty_system
         .openpty(PtySize {
-            rows: 32,
-            cols: 120,
+            rows: spec.rows,
+            cols: spec.cols,
             pixel_width: 0,
             pixel_height: 0,
         })
         .map_err(|err| format!("failed to open PTY: {}", err))?;
 
-    let command = build_pi_command(&name, &role, &id);
+    let command = build_pi_command(&name, &role, &id, &spec, sandbox.as_ref());
     let child = pair
         .slave
         .spawn_command(command)
         .map_err(|err| format!("failed to spawn pi: {}", err))?;
 
+    #[cfg(target_os = "linux")]
+    if let Some(sandbox_spec) = &sandbox {
+        if let Some(pid) = child.process_id() {
+            if let Err(err) = apply_cgroup_limits(pid, &id, sandbox_spec) {
+                eprintln!("failed to apply cgroup limits for agent {}: {}", id, err);
+            }
+        }
+    }
+
     let mut reader = pair
         .master
         .try_clone_reader()
@@ -116,17 +844,50 @@ fn spawn_agent(name: String, role: String, app: AppHandle, state: State<AppState
         .take_writer()
         .map_err(|err| format!("failed to open PTY writer: {}", err))?;
 
-    let sessions_for_thread = Arc::clone(&state.sessions);
+    {
+        let mut sessions_guard = sessions
+            .lock()
+            .map_err(|_| "failed to lock session map".to_string())?;
+
+        if let Some(session) = sessions_guard.get_mut(&id) {
+            session.state = AgentState::Starting;
+            session.runtime = SessionRuntime::Running(RunningAgent {
+                pty: pair.master,
+                writer,
+                child,
+            });
+        }
+    }
+
+    emit_state_changed(&app, &id, &AgentState::Starting);
+    let _ = app.emit("agent-started", AgentStartedEvent { id: id.clone() });
+
+    let sessions_for_thread = Arc::clone(&sessions);
+    let completed_for_thread = Arc::clone(&completed);
+    let tokens_for_thread = Arc::clone(&tokens);
     let app_for_thread = app.clone();
     let id_for_thread = id.clone();
+    let sandboxed = sandbox.is_some();
 
     thread::spawn(move || {
         let mut buffer = [0u8; 8192];
+        let mut seen_output = false;
 
         loop {
             match reader.read(&mut buffer) {
                 Ok(0) => break,
                 Ok(n) => {
+                    if let Ok(mut sessions) = sessions_for_thread.lock() {
+                        if let Some(session) = sessions.get_mut(&id_for_thread) {
+                            if !seen_output {
+                                seen_output = true;
+                                session.state = AgentState::Running;
+                                emit_state_changed(&app_for_thread, &id_for_thread, &session.state);
+                            }
+                            session.transcript.record(&buffer[..n]);
+                        }
+                    }
+
                     let _ = app_for_thread.emit(
                         "pty-output",
                         PtyOutputEvent {
@@ -139,40 +900,317 @@ fn spawn_agent(name: String, role: String, app: AppHandle, state: State<AppState
             }
         }
 
-        if let Ok(mut sessions) = sessions_for_thread.lock() {
-            sessions.remove(&id_for_thread);
+        let final_state = {
+            let mut sessions = match sessions_for_thread.lock() {
+                Ok(sessions) => sessions,
+                Err(_) => return,
+            };
+
+            let state = match sessions.get_mut(&id_for_thread).map(|s| &mut s.runtime) {
+                Some(SessionRuntime::Running(running)) => match running.child.wait() {
+                    // A clean exit with a nonzero code is still a real exit
+                    // code worth surfacing, not a crash; only a signal death
+                    // (no exit code to report) or a wait() failure is.
+                    Ok(status) if status.signal().is_none() => AgentState::Exited {
+                        code: Some(status.exit_code() as i32),
+                    },
+                    Ok(_) => AgentState::Crashed,
+                    Err(_) => AgentState::Crashed,
+                },
+                _ => AgentState::Crashed,
+            };
+
+            if let Some(session) = sessions.remove(&id_for_thread) {
+                if let Ok(mut completed) = completed_for_thread.lock() {
+                    completed.insert(
+                        id_for_thread.clone(),
+                        CompletedSession {
+                            name: session.name,
+                            role: session.role,
+                            transcript: session.transcript,
+                        },
+                    );
+                }
+            }
+            state
+        };
+
+        if sandboxed {
+            #[cfg(target_os = "linux")]
+            remove_cgroup(&id_for_thread);
         }
 
+        tokens_for_thread.release();
+
+        let code = match &final_state {
+            AgentState::Exited { code } => *code,
+            _ => None,
+        };
+
+        emit_state_changed(&app_for_thread, &id_for_thread, &final_state);
         let _ = app_for_thread.emit(
             "agent-exited",
             AgentExitedEvent {
                 id: id_for_thread,
-                code: None,
+                code,
             },
         );
     });
 
+    Ok(())
+}
+
+/// Releases the token and tears down a session that failed to start before
+/// its reader thread (which normally owns that job) ever got running.
+fn fail_to_start(
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: &Arc<Mutex<HashMap<String, CompletedSession>>>,
+    tokens: &Arc<TokenPool>,
+    app: &AppHandle,
+    id: &str,
+    sandboxed: bool,
+    err: &str,
+) {
+    if let Ok(mut sessions) = sessions.lock() {
+        if let Some(session) = sessions.remove(id) {
+            if let Ok(mut completed) = completed.lock() {
+                completed.insert(
+                    id.to_string(),
+                    CompletedSession {
+                        name: session.name,
+                        role: session.role,
+                        transcript: session.transcript,
+                    },
+                );
+            }
+        }
+    }
+    if sandboxed {
+        #[cfg(target_os = "linux")]
+        remove_cgroup(id);
+    }
+    tokens.release();
+    emit_state_changed(app, id, &AgentState::Crashed);
+    let _ = app.emit(
+        "agent-exited",
+        AgentExitedEvent {
+            id: id.to_string(),
+            code: None,
+        },
+    );
+    eprintln!("failed to start agent {}: {}", id, err);
+}
+
+#[tauri::command]
+fn spawn_agent(
+    name: String,
+    role: String,
+    sandbox: Option<SandboxSpec>,
+    record_transcript: bool,
+    template: Option<String>,
+    overrides: Option<SpawnOverrides>,
+    app: AppHandle,
+    state: State<AppState>,
+) -> Result<SpawnResult, String> {
+    let overrides = overrides.unwrap_or_default();
+
+    if sandbox.is_some() && !cfg!(target_os = "linux") {
+        return Err("sandboxed agents are only supported on Linux".to_string());
+    }
+
+    {
+        let sessions = state
+            .sessions
+            .lock()
+            .map_err(|_| "failed to lock session map".to_string())?;
+
+        if sessions.values().any(|s| s.name == name) {
+            return Err(format!("agent name \"{}\" is already running", name));
+        }
+    }
+
+    let id = Uuid::new_v4().to_string();
+
     {
         let mut sessions = state
             .sessions
             .lock()
             .map_err(|_| "failed to lock session map".to_string())?;
 
+        let disk_path = if record_transcript {
+            Some(transcript_path(&id))
+        } else {
+            None
+        };
+
         sessions.insert(
             id.clone(),
             PtySession {
                 name: name.clone(),
                 role: role.clone(),
-                pty: pair.master,
-                writer,
-                child,
+                host: "local".to_string(),
+                state: AgentState::Queued,
+                runtime: SessionRuntime::Queued,
+                transcript: Transcript::new(disk_path),
             },
         );
     }
 
+    let sandboxed = sandbox.is_some();
+
+    if state.tokens.try_acquire() {
+        if let Err(err) = start_agent_process(
+            id.clone(),
+            name.clone(),
+            role.clone(),
+            sandbox,
+            template,
+            overrides,
+            app.clone(),
+            Arc::clone(&state.sessions),
+            Arc::clone(&state.completed),
+            Arc::clone(&state.tokens),
+        ) {
+            fail_to_start(&state.sessions, &state.completed, &state.tokens, &app, &id, sandboxed, &err);
+            return Err(err);
+        }
+    } else {
+        let _ = app.emit("agent-queued", AgentQueuedEvent { id: id.clone() });
+
+        let sessions_arc = Arc::clone(&state.sessions);
+        let completed_arc = Arc::clone(&state.completed);
+        let tokens_arc = Arc::clone(&state.tokens);
+        let app_for_thread = app.clone();
+        let id_for_thread = id.clone();
+        let name_for_thread = name.clone();
+        let role_for_thread = role.clone();
+
+        thread::spawn(move || {
+            tokens_arc.acquire_blocking();
+
+            let still_queued = sessions_arc
+                .lock()
+                .map(|sessions| sessions.contains_key(&id_for_thread))
+                .unwrap_or(false);
+            if !still_queued {
+                tokens_arc.release();
+                return;
+            }
+
+            if let Err(err) = start_agent_process(
+                id_for_thread.clone(),
+                name_for_thread,
+                role_for_thread,
+                sandbox,
+                template,
+                overrides,
+                app_for_thread.clone(),
+                Arc::clone(&sessions_arc),
+                Arc::clone(&completed_arc),
+                Arc::clone(&tokens_arc),
+            ) {
+                fail_to_start(
+                    &sessions_arc,
+                    &completed_arc,
+                    &tokens_arc,
+                    &app_for_thread,
+                    &id_for_thread,
+                    sandboxed,
+                    &err,
+                );
+            }
+        });
+    }
+
     Ok(SpawnResult { id, name, role })
 }
 
+#[derive(Clone, Serialize)]
+struct TemplateInfo {
+    name: String,
+    command: String,
+    args: Vec<String>,
+    cols: u16,
+    rows: u16,
+}
+
+#[tauri::command]
+fn list_templates() -> Vec<TemplateInfo> {
+    load_hive_config()
+        .templates
+        .into_iter()
+        .map(|(name, template)| TemplateInfo {
+            name,
+            command: template.command,
+            args: template.args,
+            cols: template.cols,
+            rows: template.rows,
+        })
+        .collect()
+}
+
+#[tauri::command]
+fn set_max_concurrency(max: usize, state: State<AppState>) -> Result<(), String> {
+    state.tokens.set_max(max);
+    Ok(())
+}
+
+#[tauri::command]
+fn get_scrollback(id: String, state: State<AppState>) -> Result<Vec<u8>, String> {
+    {
+        let sessions = state
+            .sessions
+            .lock()
+            .map_err(|_| "failed to lock session map".to_string())?;
+
+        if let Some(session) = sessions.get(&id) {
+            return Ok(session.transcript.scrollback());
+        }
+    }
+
+    let completed = state
+        .completed
+        .lock()
+        .map_err(|_| "failed to lock completed-session map".to_string())?;
+
+    let session = completed
+        .get(&id)
+        .ok_or_else(|| format!("unknown agent id {}", id))?;
+
+    Ok(session.transcript.scrollback())
+}
+
+#[tauri::command]
+fn export_transcript(id: String, path: String, with_timestamps: bool, state: State<AppState>) -> Result<(), String> {
+    {
+        let mut sessions = state
+            .sessions
+            .lock()
+            .map_err(|_| "failed to lock session map".to_string())?;
+
+        if let Some(session) = sessions.get_mut(&id) {
+            return session
+                .transcript
+                .export(std::path::Path::new(&path), with_timestamps)
+                .map_err(|err| format!("export failed: {}", err));
+        }
+    }
+
+    let mut completed = state
+        .completed
+        .lock()
+        .map_err(|_| "failed to lock completed-session map".to_string())?;
+
+    let session = completed
+        .get_mut(&id)
+        .ok_or_else(|| format!("unknown agent id {}", id))?;
+
+    session
+        .transcript
+        .export(std::path::Path::new(&path), with_timestamps)
+        .map_err(|err| format!("export failed: {}", err))
+}
+
 #[tauri::command]
 fn list_agents(state: State<AppState>) -> Result<Vec<AgentInfo>, String> {
     let sessions = state
@@ -186,6 +1224,8 @@ fn list_agents(state: State<AppState>) -> Result<Vec<AgentInfo>, String> {
             id: id.clone(),
             name: session.name.clone(),
             role: session.role.clone(),
+            host: session.host.clone(),
+            state: session.state.clone(),
         })
         .collect::<Vec<_>>();
 
@@ -193,7 +1233,7 @@ fn list_agents(state: State<AppState>) -> Result<Vec<AgentInfo>, String> {
 }
 
 #[tauri::command]
-fn write_pty(id: String, data: String, state: State<AppState>) -> Result<(), String> {
+fn set_agent_state(id: String, activity: AgentActivity, app: AppHandle, state: State<AppState>) -> Result<(), String> {
     let mut sessions = state
         .sessions
         .lock()
@@ -203,17 +1243,40 @@ fn write_pty(id: String, data: String, state: State<AppState>) -> Result<(), Str
         .get_mut(&id)
         .ok_or_else(|| format!("unknown agent id {}", id))?;
 
-    session
-        .writer
-        .write_all(data.as_bytes())
-        .map_err(|err| format!("write failed: {}", err))?;
-    session
-        .writer
-        .flush()
-        .map_err(|err| format!("flush failed: {}", err))?;
+    if matches!(session.runtime, SessionRuntime::Queued) {
+        return Err(format!("agent {} is still queued", id));
+    }
+
+    session.state = match activity {
+        AgentActivity::Idle => AgentState::Idle,
+        AgentActivity::Busy => AgentState::Busy,
+    };
+    emit_state_changed(&app, &id, &session.state);
+
     Ok(())
 }
 
+#[tauri::command]
+fn write_pty(id: String, data: String, state: State<AppState>) -> Result<(), String> {
+    let mut sessions = state
+        .sessions
+        .lock()
+        .map_err(|_| "failed to lock session map".to_string())?;
+
+    let session = sessions
+        .get_mut(&id)
+        .ok_or_else(|| format!("unknown agent id {}", id))?;
+
+    let handle = session
+        .runtime
+        .handle_mut()
+        .ok_or_else(|| format!("agent {} is still queued", id))?;
+
+    handle
+        .write(data.as_bytes())
+        .map_err(|err| format!("write failed: {}", err))
+}
+
 #[tauri::command]
 fn resize_pty(id: String, cols: u16, rows: u16, state: State<AppState>) -> Result<(), String> {
     let mut sessions = state
@@ -225,55 +1288,81 @@ fn resize_pty(id: String, cols: u16, rows: u16, state: State<AppState>) -> Resul
         .get_mut(&id)
         .ok_or_else(|| format!("unknown agent id {}", id))?;
 
-    session
-        .pty
-        .resize(PtySize {
-            rows,
-            cols,
-            pixel_width: 0,
-            pixel_height: 0,
-        })
-        .map_err(|err| format!("resize failed: {}", err))?;
+    let handle = session
+        .runtime
+        .handle_mut()
+        .ok_or_else(|| format!("agent {} is still queued", id))?;
 
-    Ok(())
+    handle
+        .resize(cols, rows)
+        .map_err(|err| format!("resize failed: {}", err))
 }
 
 #[tauri::command]
-fn kill_agent(id: String, app: AppHandle, state: State<AppState>) -> Result<(), String> {
-    let maybe_session = {
-        let mut sessions = state
-            .sessions
-            .lock()
-            .map_err(|_| "failed to lock session map".to_string())?;
-        sessions.remove(&id)
+fn kill_agent(id: String, state: State<AppState>) -> Result<(), String> {
+    // Only signal here; for a local agent the reader thread owns exit
+    // detection and cleanup (so a killed agent still gets a real
+    // `child.wait()` status and a single agent-exited/agent-state-changed
+    // pair instead of a racing one), and for a remote agent the worker's own
+    // exit report over the control channel drives the same cleanup path.
+    let mut sessions = state
+        .sessions
+        .lock()
+        .map_err(|_| "failed to lock session map".to_string())?;
+
+    let is_queued = match sessions.get(&id) {
+        Some(session) => matches!(session.runtime, SessionRuntime::Queued),
+        None => return Err(format!("unknown agent id {}", id)),
     };
 
-    let mut session = maybe_session.ok_or_else(|| format!("unknown agent id {}", id))?;
-    session
-        .child
-        .kill()
-        .map_err(|err| format!("kill failed: {}", err))?;
+    if is_queued {
+        // Never got a token, so there's no process to kill and no token to
+        // release; just drop the queue entry. The still-parked background
+        // thread checks for this before starting the process.
+        sessions.remove(&id);
+        return Ok(());
+    }
 
-    let _ = app.emit(
-        "agent-exited",
-        AgentExitedEvent {
-            id,
-            code: None,
-        },
-    );
+    let session = sessions
+        .get_mut(&id)
+        .ok_or_else(|| format!("unknown agent id {}", id))?;
 
-    Ok(())
+    let handle = session
+        .runtime
+        .handle_mut()
+        .ok_or_else(|| format!("agent {} is still queued", id))?;
+
+    handle.kill().map_err(|err| format!("kill failed: {}", err))
 }
 
 fn main() {
     tauri::Builder::default()
         .manage(AppState::default())
+        .setup(|app| {
+            if let Some(remote_config) = load_hive_config().remote {
+                let state = app.state::<AppState>();
+                remote::spawn_listener(
+                    remote_config,
+                    app.handle().clone(),
+                    Arc::clone(&state.sessions),
+                    Arc::clone(&state.completed),
+                );
+            }
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             spawn_agent,
             list_agents,
             write_pty,
             resize_pty,
             kill_agent,
+            set_agent_state,
+            dispatch_job,
+            poll_completed_jobs,
+            set_max_concurrency,
+            get_scrollback,
+            export_transcript,
+            list_templates,
         ])
         .run(tauri::generate_context!())
         .expect("error while running hive desktop");
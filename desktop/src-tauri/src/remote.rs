@@ -0,0 +1,385 @@
+//! Controller side of hive's remote-agent protocol. A lightweight worker
+//! binary (out of scope for this crate) runs on another host, opens a TLS
+//! connection back to hive, registers itself by presenting the shared secret
+//! hive issued when it was provisioned, and then proxies a PTY it owns over
+//! that same link. This mirrors the server/agent split unki uses for its
+//! managed agents, and reuses the length-prefixed framing that
+//! `async-native-tls`-based integrations expect when several messages may be
+//! pipelined on one connection.
+//!
+//! The TLS handshake itself is one-way (workers verify hive's certificate,
+//! not the other way around); `registration_secret` is what actually proves a
+//! connecting worker was provisioned, and `handle_worker` drops the
+//! connection before inserting a session if the `Register` frame doesn't
+//! carry it.
+//!
+//! Everything here runs on plain OS threads rather than an async runtime, to
+//! match the thread-per-session model `start_agent_process` already uses for
+//! local agents: one thread accepts connections, and one more per connected
+//! worker reads frames and drives that session's lifecycle exactly the way
+//! the local reader thread does.
+
+use crate::{
+    emit_state_changed, AgentExitedEvent, AgentHandle, AgentState, AgentStartedEvent, CompletedSession,
+    PtyOutputEvent, PtySession, SessionRuntime, Transcript,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+/// Settings for the `[remote]` table in `hive.toml`. `identity_path` is a
+/// PKCS#12 bundle (cert + private key) hive presents to connecting workers.
+/// `registration_secret` is the token whoever runs hive hands out when
+/// provisioning a worker; `handle_worker` requires it on the `Register` frame
+/// before trusting a connection, since the TLS handshake alone only proves
+/// the worker is talking to hive, not that hive should talk back to it.
+#[derive(Clone, Deserialize)]
+pub(crate) struct RemoteConfig {
+    pub(crate) listen_addr: String,
+    pub(crate) identity_path: String,
+    pub(crate) identity_password: String,
+    pub(crate) registration_secret: String,
+}
+
+/// Frames exchanged over the control channel. The worker sends `Register`
+/// exactly once, first; after that the worker sends `Output`/`Exited` and
+/// hive sends `Write`/`Resize`/`Kill`, at any point.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ControlMessage {
+    Register {
+        name: String,
+        role: String,
+        host: String,
+        secret: String,
+    },
+    Output { data: Vec<u8> },
+    Write { data: Vec<u8> },
+    Resize { cols: u16, rows: u16 },
+    Kill,
+    Exited { code: Option<i32> },
+}
+
+fn write_frame<W: Write>(writer: &mut W, message: &ControlMessage) -> std::io::Result<()> {
+    let payload =
+        serde_json::to_vec(message).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+    writer.write_all(&payload)?;
+    writer.flush()
+}
+
+fn read_frame<R: Read>(reader: &mut R) -> std::io::Result<ControlMessage> {
+    let mut len_buf = [0u8; 4];
+    reader.read_exact(&mut len_buf)?;
+    let len = u32::from_le_bytes(len_buf) as usize;
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload)?;
+    serde_json::from_slice(&payload).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// How long a single read attempt in `FrameReader::poll` is allowed to block
+/// before giving `handle_worker` a chance to release the stream's lock for a
+/// pending `write`/`resize`/`kill` (see `FrameReader`).
+const FRAME_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
+
+fn is_retryable_timeout(err: &std::io::Error) -> bool {
+    matches!(err.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+/// Assembles one length-prefixed frame across however many short reads it
+/// takes, so `handle_worker`'s reader loop can poll the stream with a read
+/// timeout and drop its lock between attempts instead of holding it for the
+/// whole blocking wait on the worker's next frame. State persists across
+/// `poll` calls that return `Ok(None)` (nothing complete yet).
+struct FrameReader {
+    header: [u8; 4],
+    header_filled: usize,
+    payload: Vec<u8>,
+    payload_filled: usize,
+    payload_len: Option<usize>,
+}
+
+impl FrameReader {
+    fn new() -> Self {
+        FrameReader {
+            header: [0u8; 4],
+            header_filled: 0,
+            payload: Vec::new(),
+            payload_filled: 0,
+            payload_len: None,
+        }
+    }
+
+    /// Reads whatever is immediately available. Returns `Ok(None)` if the
+    /// read timed out before a full frame arrived, `Ok(Some(_))` once one
+    /// has been assembled, or `Err` on a real I/O failure or disconnect.
+    fn poll<R: Read>(&mut self, reader: &mut R) -> std::io::Result<Option<ControlMessage>> {
+        if self.payload_len.is_none() {
+            while self.header_filled < self.header.len() {
+                match reader.read(&mut self.header[self.header_filled..]) {
+                    Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "worker closed the connection")),
+                    Ok(n) => self.header_filled += n,
+                    Err(err) if is_retryable_timeout(&err) => return Ok(None),
+                    Err(err) => return Err(err),
+                }
+            }
+            let len = u32::from_le_bytes(self.header) as usize;
+            self.payload = vec![0u8; len];
+        }
+        self.payload_len = Some(self.payload.len());
+
+        while self.payload_filled < self.payload.len() {
+            match reader.read(&mut self.payload[self.payload_filled..]) {
+                Ok(0) => return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "worker closed the connection")),
+                Ok(n) => self.payload_filled += n,
+                Err(err) if is_retryable_timeout(&err) => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+
+        let message = serde_json::from_slice(&self.payload)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        self.header_filled = 0;
+        self.payload_filled = 0;
+        self.payload_len = None;
+        Ok(Some(message))
+    }
+}
+
+/// The remote half of `SessionRuntime`: a `PtySession` backed by a worker on
+/// another host instead of a local `portable_pty` child. Implements
+/// `AgentHandle` the same way `RunningAgent` does, just sending the command
+/// across the wire instead of calling into the PTY/child directly.
+pub(crate) struct RemoteSession {
+    stream: Arc<Mutex<native_tls::TlsStream<TcpStream>>>,
+}
+
+impl AgentHandle for RemoteSession {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        write_frame(&mut *stream, &ControlMessage::Write { data: data.to_vec() })
+    }
+
+    fn resize(&mut self, cols: u16, rows: u16) -> std::io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        write_frame(&mut *stream, &ControlMessage::Resize { cols, rows })
+    }
+
+    fn kill(&mut self) -> std::io::Result<()> {
+        let mut stream = self.stream.lock().unwrap();
+        write_frame(&mut *stream, &ControlMessage::Kill)
+    }
+}
+
+/// Binds `config.listen_addr` and, for each worker that connects and
+/// completes the TLS handshake, hands it off to `handle_worker` on its own
+/// thread. Runs for the lifetime of the app; a bind or identity failure is
+/// logged and simply leaves remote agents unavailable rather than taking
+/// down the rest of hive.
+pub(crate) fn spawn_listener(
+    config: RemoteConfig,
+    app: AppHandle,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: Arc<Mutex<HashMap<String, CompletedSession>>>,
+) {
+    thread::spawn(move || {
+        let identity_bytes = match std::fs::read(&config.identity_path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                eprintln!("remote: failed to read identity {}: {}", config.identity_path, err);
+                return;
+            }
+        };
+
+        let identity = match native_tls::Identity::from_pkcs12(&identity_bytes, &config.identity_password) {
+            Ok(identity) => identity,
+            Err(err) => {
+                eprintln!("remote: failed to load TLS identity: {}", err);
+                return;
+            }
+        };
+
+        let acceptor = match native_tls::TlsAcceptor::new(identity) {
+            Ok(acceptor) => acceptor,
+            Err(err) => {
+                eprintln!("remote: failed to build TLS acceptor: {}", err);
+                return;
+            }
+        };
+
+        let listener = match TcpListener::bind(&config.listen_addr) {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("remote: failed to bind {}: {}", config.listen_addr, err);
+                return;
+            }
+        };
+
+        for incoming in listener.incoming() {
+            let tcp_stream = match incoming {
+                Ok(tcp_stream) => tcp_stream,
+                Err(_) => continue,
+            };
+
+            let acceptor = acceptor.clone();
+            let app = app.clone();
+            let sessions = Arc::clone(&sessions);
+            let completed = Arc::clone(&completed);
+            let registration_secret = config.registration_secret.clone();
+
+            thread::spawn(move || match acceptor.accept(tcp_stream) {
+                Ok(tls_stream) => handle_worker(tls_stream, app, sessions, completed, registration_secret),
+                Err(err) => eprintln!("remote: TLS handshake failed: {}", err),
+            });
+        }
+    });
+}
+
+/// Owns one worker connection end to end: reads its `Register` frame, checks
+/// it against `registration_secret` before trusting the connection at all,
+/// inserts a `PtySession` for it, then loops reading `Output`/`Exited` frames
+/// and feeding them into the same transcript/event pipeline local agents use,
+/// until the worker disconnects or reports its process exited. Mirrors how
+/// `start_agent_process`'s reader thread is the sole owner of a local
+/// session's lifecycle and cleanup.
+fn handle_worker(
+    stream: native_tls::TlsStream<TcpStream>,
+    app: AppHandle,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: Arc<Mutex<HashMap<String, CompletedSession>>>,
+    registration_secret: String,
+) {
+    let stream = Arc::new(Mutex::new(stream));
+
+    let registration = {
+        let mut guard = stream.lock().unwrap();
+        read_frame(&mut *guard)
+    };
+
+    let (name, role, host) = match registration {
+        Ok(ControlMessage::Register { name, role, host, secret }) if secret == registration_secret => {
+            (name, role, host)
+        }
+        Ok(ControlMessage::Register { .. }) => {
+            eprintln!("remote: worker presented the wrong registration secret, dropping connection");
+            return;
+        }
+        _ => {
+            eprintln!("remote: worker did not send a valid registration, dropping connection");
+            return;
+        }
+    };
+
+    let id = Uuid::new_v4().to_string();
+
+    {
+        let mut sessions_guard = sessions.lock().unwrap();
+        if sessions_guard.values().any(|session| session.name == name) {
+            eprintln!("remote: agent name \"{}\" is already running, dropping connection", name);
+            return;
+        }
+
+        sessions_guard.insert(
+            id.clone(),
+            PtySession {
+                name,
+                role,
+                host,
+                state: AgentState::Running,
+                runtime: SessionRuntime::Remote(RemoteSession { stream: Arc::clone(&stream) }),
+                transcript: Transcript::new(None),
+            },
+        );
+    }
+
+    emit_state_changed(&app, &id, &AgentState::Running);
+    let _ = app.emit("agent-started", AgentStartedEvent { id: id.clone() });
+
+    // A short read timeout lets the loop below release `stream`'s lock
+    // between attempts instead of holding it for the whole blocking wait on
+    // the worker's next frame, which would otherwise starve `write`/`resize`/
+    // `kill` (the other `AgentHandle` methods, which also lock `stream`)
+    // whenever the remote agent is idle.
+    {
+        let guard = stream.lock().unwrap();
+        let _ = guard.get_ref().set_read_timeout(Some(FRAME_POLL_INTERVAL));
+    }
+
+    let mut frame_reader = FrameReader::new();
+
+    loop {
+        let message = 'poll: loop {
+            let mut guard = stream.lock().unwrap();
+            match frame_reader.poll(&mut *guard) {
+                Ok(Some(message)) => break 'poll Ok(message),
+                Ok(None) => {}
+                Err(err) => break 'poll Err(err),
+            }
+        };
+
+        match message {
+            Ok(ControlMessage::Output { data }) => {
+                if let Ok(mut sessions) = sessions.lock() {
+                    if let Some(session) = sessions.get_mut(&id) {
+                        session.transcript.record(&data);
+                    }
+                }
+                let _ = app.emit("pty-output", PtyOutputEvent { id: id.clone(), data });
+            }
+            Ok(ControlMessage::Exited { code }) => {
+                finish(&sessions, &completed, &app, &id, AgentState::Exited { code });
+                return;
+            }
+            Ok(_) => {
+                // Write/Resize/Kill only ever flow controller -> worker, and
+                // Register is only read once, before this loop; ignore them.
+            }
+            Err(_) => {
+                finish(&sessions, &completed, &app, &id, AgentState::Crashed);
+                return;
+            }
+        }
+    }
+}
+
+fn finish(
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    completed: &Arc<Mutex<HashMap<String, CompletedSession>>>,
+    app: &AppHandle,
+    id: &str,
+    state: AgentState,
+) {
+    if let Ok(mut sessions) = sessions.lock() {
+        if let Some(session) = sessions.remove(id) {
+            if let Ok(mut completed) = completed.lock() {
+                completed.insert(
+                    id.to_string(),
+                    CompletedSession {
+                        name: session.name,
+                        role: session.role,
+                        transcript: session.transcript,
+                    },
+                );
+            }
+        }
+    }
+
+    let code = match &state {
+        AgentState::Exited { code } => *code,
+        _ => None,
+    };
+
+    emit_state_changed(app, id, &state);
+    let _ = app.emit(
+        "agent-exited",
+        AgentExitedEvent {
+            id: id.to_string(),
+            code,
+        },
+    );
+}